@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+use std::fs;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::registers;
+
+#[derive(Deserialize, Clone)]
+pub struct DeviceConfig {
+    pub name: String,
+    pub ip: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_unit_id")]
+    pub unit_id: u8,
+    pub register_profile: String,
+}
+
+fn default_port() -> u16 {
+    502
+}
+
+fn default_unit_id() -> u8 {
+    1
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub device: Vec<DeviceConfig>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("cannot read config file at {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+    #[error("cannot parse config file at {path}: {source}")]
+    Parse { path: String, source: toml::de::Error },
+    #[error("config defines no devices")]
+    NoDevices,
+    #[error("duplicate device name {0:?} in config")]
+    DuplicateDevice(String),
+    #[error("device {name:?} has unknown register profile {profile:?}")]
+    UnknownRegisterProfile { name: String, profile: String },
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read { path: path.to_string(), source })?;
+        let config: Config = toml::from_str(&contents)
+            .map_err(|source| ConfigError::Parse { path: path.to_string(), source })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.device.is_empty() {
+            return Err(ConfigError::NoDevices);
+        }
+
+        let mut seen_names = HashSet::new();
+        for device in &self.device {
+            if !seen_names.insert(device.name.clone()) {
+                return Err(ConfigError::DuplicateDevice(device.name.clone()));
+            }
+            if registers::profile(&device.register_profile).is_none() {
+                return Err(ConfigError::UnknownRegisterProfile {
+                    name: device.name.clone(),
+                    profile: device.register_profile.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str, register_profile: &str) -> DeviceConfig {
+        DeviceConfig {
+            name: name.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: default_port(),
+            unit_id: default_unit_id(),
+            register_profile: register_profile.to_string(),
+        }
+    }
+
+    #[test]
+    fn validate_accepts_a_well_formed_config() {
+        let config = Config { device: vec![device("inverter-1", "sungold-default")] };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_an_empty_device_list() {
+        let config = Config { device: vec![] };
+        assert!(matches!(config.validate(), Err(ConfigError::NoDevices)));
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_device_names() {
+        let config = Config {
+            device: vec![
+                device("inverter-1", "sungold-default"),
+                device("inverter-1", "sungold-default"),
+            ],
+        };
+        match config.validate() {
+            Err(ConfigError::DuplicateDevice(name)) => assert_eq!(name, "inverter-1"),
+            other => panic!("expected DuplicateDevice, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_an_unknown_register_profile() {
+        let config = Config { device: vec![device("inverter-1", "no-such-profile")] };
+        match config.validate() {
+            Err(ConfigError::UnknownRegisterProfile { name, profile }) => {
+                assert_eq!(name, "inverter-1");
+                assert_eq!(profile, "no-such-profile");
+            }
+            other => panic!("expected UnknownRegisterProfile, got {:?}", other),
+        }
+    }
+}
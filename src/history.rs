@@ -0,0 +1,214 @@
+use std::path::Path;
+
+use lmdb::{Cursor, Environment, Database, Transaction, WriteFlags};
+use rocket::serde::Serialize;
+use thiserror::Error;
+
+use crate::MinerData;
+
+/// Rough upper bound on one flexbuffers-encoded `MinerData` sample's on-disk
+/// footprint, used only to size the LMDB map. LMDB only reserves virtual
+/// address space for the map (not disk), so it's fine to pad generously.
+const BYTES_PER_SAMPLE_ESTIMATE: usize = 512;
+
+/// Floor for the map size so a short retention window still leaves
+/// comfortable headroom for growth.
+const MIN_MAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// Sizes the LMDB map from the retention window and poll interval, instead
+/// of a fixed constant, so raising `HISTORY_RETENTION_SECS` doesn't silently
+/// run into `MDB_MAP_FULL` once the window holds more samples than a fixed
+/// size was ever meant to.
+fn map_size_for(retention_secs: u64, poll_interval_secs: u64) -> usize {
+    let sample_count = (retention_secs / poll_interval_secs.max(1)).saturating_add(1);
+    let estimated = (sample_count as usize)
+        .saturating_mul(BYTES_PER_SAMPLE_ESTIMATE)
+        .saturating_mul(2);
+    estimated.max(MIN_MAP_SIZE)
+}
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("cannot open LMDB environment at {path}: {source}")]
+    EnvOpen { path: String, source: lmdb::Error },
+    #[error("LMDB transaction failed: {0}")]
+    Transaction(#[from] lmdb::Error),
+    #[error("cannot encode sample: {0}")]
+    Encode(String),
+    #[error("cannot decode sample: {0}")]
+    Decode(String),
+}
+
+/// An embedded, append-mostly time-series store for `MinerData` samples.
+///
+/// Samples are keyed by their big-endian `u64` unix timestamp so that LMDB's
+/// natural key ordering is also chronological order, which lets range queries
+/// and pruning both work as plain cursor scans.
+pub struct HistoryStore {
+    env: Environment,
+    db: Database,
+    retention_secs: u64,
+}
+
+impl HistoryStore {
+    pub fn open(path: &str, retention_secs: u64, poll_interval_secs: u64) -> Result<Self, HistoryError> {
+        std::fs::create_dir_all(path).ok();
+        let env = Environment::new()
+            .set_map_size(map_size_for(retention_secs, poll_interval_secs))
+            .open(Path::new(path))
+            .map_err(|source| HistoryError::EnvOpen { path: path.to_string(), source })?;
+        let db = env.open_db(None)?;
+        Ok(Self { env, db, retention_secs })
+    }
+
+    /// Persists a single sample under `timestamp`, then prunes anything older
+    /// than the configured retention window.
+    pub fn record(&self, timestamp: u64, data: &MinerData) -> Result<(), HistoryError> {
+        let key = timestamp.to_be_bytes();
+        let mut buf = flexbuffers::FlexbufferSerializer::new();
+        data.serialize(&mut buf).map_err(|e| HistoryError::Encode(e.to_string()))?;
+
+        let mut txn = self.env.begin_rw_txn()?;
+        txn.put(self.db, &key, &buf.view(), WriteFlags::empty())?;
+        txn.commit()?;
+
+        self.prune_before(timestamp.saturating_sub(self.retention_secs))
+    }
+
+    /// Returns every sample with `from <= timestamp <= to`. When `step` is
+    /// larger than the underlying poll interval, only the first sample in
+    /// each `step`-wide bucket is kept (simple decimation).
+    pub fn query(&self, from: u64, to: u64, step: u64) -> Result<Vec<(u64, MinerData)>, HistoryError> {
+        let txn = self.env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(self.db)?;
+
+        let mut samples = Vec::new();
+        let mut next_bucket = from;
+
+        for entry in cursor.iter_from(&from.to_be_bytes()) {
+            let (key, value) = entry?;
+            let timestamp = key_to_timestamp(key);
+            if timestamp > to {
+                break;
+            }
+            if step > 0 && timestamp < next_bucket {
+                continue;
+            }
+
+            let data = flexbuffers::from_slice::<MinerData>(value)
+                .map_err(|e| HistoryError::Decode(e.to_string()))?;
+            samples.push((timestamp, data));
+
+            if step > 0 {
+                next_bucket = timestamp + step;
+            }
+        }
+
+        Ok(samples)
+    }
+
+    fn prune_before(&self, cutoff: u64) -> Result<(), HistoryError> {
+        let stale_keys: Vec<[u8; 8]> = {
+            let txn = self.env.begin_ro_txn()?;
+            let mut cursor = txn.open_ro_cursor(self.db)?;
+            cursor
+                .iter_start()
+                .filter_map(|entry| entry.ok())
+                .take_while(|(key, _)| key_to_timestamp(key) < cutoff)
+                .map(|(key, _)| {
+                    let mut owned = [0u8; 8];
+                    owned.copy_from_slice(key);
+                    owned
+                })
+                .collect()
+        };
+
+        if stale_keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut txn = self.env.begin_rw_txn()?;
+        for key in &stale_keys {
+            txn.del(self.db, key, None)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+fn key_to_timestamp(key: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(key);
+    u64::from_be_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhasePower;
+
+    fn open_temp_store(name: &str, retention_secs: u64, poll_interval_secs: u64) -> HistoryStore {
+        let path = std::env::temp_dir()
+            .join(format!("sungoldminer-history-test-{}-{}", std::process::id(), name));
+        std::fs::remove_dir_all(&path).ok();
+        HistoryStore::open(path.to_str().unwrap(), retention_secs, poll_interval_secs).unwrap()
+    }
+
+    fn sample(imported_power_total: f64) -> MinerData {
+        let phase = PhasePower { power: 0.0, reactive_power: 0.0, apparent_power: 0.0 };
+        MinerData {
+            imported_power_total,
+            imported_reactive_power_total: 0.0,
+            exported_power_total: 0.0,
+            exported_reactive_power_total: 0.0,
+            phase1: phase,
+            phase2: phase,
+            phase3: phase,
+        }
+    }
+
+    #[test]
+    fn query_is_inclusive_of_from_and_to_bounds() {
+        let store = open_temp_store("bounds", 1_000_000, 10);
+        store.record(100, &sample(1.0)).unwrap();
+        store.record(200, &sample(2.0)).unwrap();
+        store.record(300, &sample(3.0)).unwrap();
+
+        let all = store.query(100, 300, 0).unwrap();
+        let timestamps: Vec<u64> = all.iter().map(|(ts, _)| *ts).collect();
+        assert_eq!(timestamps, vec![100, 200, 300]);
+
+        let just_from = store.query(100, 100, 0).unwrap();
+        assert_eq!(just_from.len(), 1);
+        assert_eq!(just_from[0].0, 100);
+    }
+
+    #[test]
+    fn query_decimates_by_keeping_the_first_sample_in_each_step_bucket() {
+        let store = open_temp_store("decimate", 1_000_000, 10);
+        for ts in [100, 110, 120, 130, 140] {
+            store.record(ts, &sample(ts as f64)).unwrap();
+        }
+
+        let decimated = store.query(100, 140, 20).unwrap();
+        let timestamps: Vec<u64> = decimated.iter().map(|(ts, _)| *ts).collect();
+        assert_eq!(timestamps, vec![100, 120, 140]);
+
+        let undecimated = store.query(100, 140, 0).unwrap();
+        assert_eq!(undecimated.len(), 5);
+    }
+
+    #[test]
+    fn record_prunes_samples_older_than_the_retention_window() {
+        let store = open_temp_store("prune", 50, 10);
+        store.record(1000, &sample(1.0)).unwrap();
+        store.record(1040, &sample(2.0)).unwrap();
+        // Cutoff is now 1060 - 50 = 1010, so the sample at 1000 falls outside
+        // the retention window and should be pruned away on this write.
+        store.record(1060, &sample(3.0)).unwrap();
+
+        let remaining = store.query(0, 10_000, 0).unwrap();
+        let timestamps: Vec<u64> = remaining.iter().map(|(ts, _)| *ts).collect();
+        assert_eq!(timestamps, vec![1040, 1060]);
+    }
+}
@@ -1,124 +1,152 @@
 #[macro_use]
 extern crate rocket;
 use rocket::State;
-use rocket::http::Status;
+use rocket::http::{ContentType, Status};
 use rocket::serde::json::Json;
-use rocket::serde::Serialize;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::fairing::AdHoc;
+use rocket::tokio::time::{interval, Duration};
 use dotenv::dotenv;
 use thiserror::Error;
 use modbus::Client;
 use modbus::tcp;
 use std::env;
-use phf::phf_map;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::collections::HashMap;
 
-struct MinerState {
-    client_ip: String,
+mod config;
+mod history;
+mod metrics;
+mod registers;
+use config::{Config, DeviceConfig};
+use history::HistoryStore;
+use registers::{PhaseField, RegisterSpec};
+
+/// Capped exponential backoff applied between reconnect/retry attempts when
+/// a register read fails, so a single dropped packet doesn't turn into a
+/// request failure.
+const RETRY_BACKOFFS_MS: [u64; 3] = [50, 100, 200];
+
+/// A single device's connection and everything needed to (re-)open it. The
+/// transport itself is opened lazily, on first read, so one unreachable
+/// meter at boot doesn't take the whole server down with it.
+struct DeviceConnection {
+    name: String,
+    address: String,
+    unit_id: u8,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    profile: &'static registers::RegisterProfile,
+    transport: Mutex<Option<tcp::Transport>>,
+}
+
+impl DeviceConnection {
+    fn new(device: &DeviceConfig, connect_timeout: Duration, read_timeout: Duration) -> Self {
+        let address = format!("{}:{}", device.ip, device.port);
+        let profile = registers::profile(&device.register_profile)
+            .expect("Config::load already validated register_profile");
+
+        Self {
+            name: device.name.clone(),
+            address,
+            unit_id: device.unit_id,
+            connect_timeout,
+            read_timeout,
+            profile,
+            transport: Mutex::new(None),
+        }
+    }
 }
 
-#[derive(Serialize, Clone, Copy)]
+type DeviceConnections = HashMap<String, Arc<DeviceConnection>>;
+type DeviceHistories = HashMap<String, Arc<HistoryStore>>;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
 struct PhasePower {
-    power: u32,
+    pub(crate) power: f64,
     #[serde(rename = "reactivePower")]
-    reactive_power: u32,
+    pub(crate) reactive_power: f64,
     #[serde(rename = "apparentPower")]
-    apparent_power: u32,
+    pub(crate) apparent_power: f64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct MinerData {
     #[serde(rename = "importedPowerTotal")]
-    imported_power_total: u32,
+    pub(crate) imported_power_total: f64,
     #[serde(rename = "importedReactivePowerTotal")]
-    imported_reactive_power_total: u32,
+    pub(crate) imported_reactive_power_total: f64,
     #[serde(rename = "exportedPowerTotal")]
-    exported_power_total: u32,
+    pub(crate) exported_power_total: f64,
     #[serde(rename = "exportedReactivePowerTotal")]
-    exported_reactive_power_total: u32,
+    pub(crate) exported_reactive_power_total: f64,
 
-    phase1: PhasePower,
-    phase2: PhasePower,
-    phase3: PhasePower,
+    pub(crate) phase1: PhasePower,
+    pub(crate) phase2: PhasePower,
+    pub(crate) phase3: PhasePower,
 }
 
 #[derive(Error,Debug)]
 enum MinerError {
-    #[error("Cannot read u32 from register {register:#x}: {message}")]
-    BadDataRead{
-        register: u16,
-        message: String
-    },
     #[error("Cannot open Modbus TCP transport to pull data: {0}")]
     ModbusTransportIssue(String),
 }
 
-#[derive(PartialEq, Eq, Hash)]
-enum PhaseFields {
-    Power,
-    ApparentPower,
-    ReactivePower,
+fn open_transport(address: &str, unit_id: u8, connect_timeout: Duration, read_timeout: Duration) -> Result<tcp::Transport, modbus::Error> {
+    let mut cfg = tcp::Config::default();
+    cfg.tcp_connect_timeout = Some(connect_timeout);
+    cfg.tcp_read_timeout = Some(read_timeout);
+    cfg.tcp_write_timeout = Some(read_timeout);
+    cfg.modbus_uid = unit_id;
+    tcp::Transport::new_with_cfg(address, cfg)
 }
 
-static BASE_DATA_REGISTER: phf::Map<&'static str, u16> = phf_map! {
-    "imported_power_total" => 0x34,
-    "imported_reactive_power_total" => 0x36,
-    "exported_power_total" => 0x4e,
-    "exported_reactive_power_total" => 0x50,
-};
-
-
 /**
- * Polls for all of the desired data from the power analyzer.
- * 
- * This populates the entire struct and will trigger a large amount of TCP queries
- * on one individual connection to save connection energy.
+ * Polls for all of the desired data from one device's power analyzer.
+ *
+ * This reuses the single `tcp::Transport` held in its `DeviceConnection`
+ * instead of opening a new connection per call. This populates the entire
+ * struct and will trigger a large amount of TCP queries on one individual
+ * connection to save connection energy.
  */
-fn poll_solar_data(client_ip: String) -> Result<MinerData, MinerError> {
-    // Create a client first.
-    let mut client = tcp::Transport::new(&client_ip)
-      .or_else(|e| Err(MinerError::ModbusTransportIssue(e.to_string())))?;
+fn poll_solar_data(connection: &DeviceConnection) -> Result<MinerData, MinerError> {
+    let mut client = connection.transport.lock().unwrap();
 
     // Create the struct we'll need, but first setup all the reads.
     // For the purposes of experimentation, we'll use our constant map to get
     // the keys for each value stored in a separate map and then add them to our enum.
     //
     // Let's make the base map first.
-    let mut polled_data: HashMap<&'static str, u32> = HashMap::new();
+    let mut polled_data: HashMap<&'static str, f64> = HashMap::new();
 
     // We'll then make an extra array that stores the PhaseData for each phase.
     let mut phase_data: Vec<PhasePower> = Vec::new();
 
     // For all the base values, just add them to the map.
-    for (field_name, register) in &BASE_DATA_REGISTER {
-        let value = read_modbus_int32(&mut client, *register)
-          .or_else(|e| Err(MinerError::BadDataRead { register: *register, message: e.to_string() }))?;
+    for (field_name, spec) in connection.profile.base {
+        let value = read_register_with_retry(connection, &mut client, spec)?;
 
         polled_data.insert(*field_name, value);
     }
 
-    // A map to more easily store the addresses for the phase power registers.
-    let phase_data_registers: HashMap<PhaseFields, u16> = HashMap::from(
-        [
-         (PhaseFields::Power, 0x12),
-         (PhaseFields::ApparentPower, 0x18),
-         (PhaseFields::ReactivePower, 0x1e),
-        ]
-    );
+    // A map to more easily store the register specs for the phase power fields.
+    let phase_data_registers = (connection.profile.phase)();
 
     // For the three phases, just offset by 2 16-bit words for each value.
     // Go through each necessary field and just go to phase_0_register + i * 0x2
     // to get the value needed.
     for i in 0..3 {
-        let mut power = 0;
-        let mut apparent_power = 0;
-        let mut reactive_power = 0;
-        for (field_name, register) in &phase_data_registers {
-            let value = read_modbus_int32(&mut client, register + i * 0x2)
-                      .or_else(|e| Err(MinerError::BadDataRead { register: *register, message: e.to_string() }))?;
+        let mut power = 0.0;
+        let mut apparent_power = 0.0;
+        let mut reactive_power = 0.0;
+        for (field_name, spec) in &phase_data_registers {
+            let value = read_register_with_retry(connection, &mut client, &spec.offset(i * 0x2))?;
             match *field_name {
-                PhaseFields::Power => power = value,
-                PhaseFields::ApparentPower => apparent_power = value,
-                PhaseFields::ReactivePower => reactive_power = value,
+                PhaseField::Power => power = value,
+                PhaseField::ApparentPower => apparent_power = value,
+                PhaseField::ReactivePower => reactive_power = value,
             }
         }
 
@@ -129,9 +157,6 @@ fn poll_solar_data(client_ip: String) -> Result<MinerData, MinerError> {
         });
     }
 
-    // Close the client up.
-    client.close().or_else(|e| Err(MinerError::ModbusTransportIssue(e.to_string())))?;
-
     // Unwraps are not nice here, but they're fine, because allegedly
     // all the fields in the map are populated at this point.
     Ok(MinerData{
@@ -145,31 +170,187 @@ fn poll_solar_data(client_ip: String) -> Result<MinerData, MinerError> {
     })
 }
 
-fn read_modbus_int32(client: &mut tcp::Transport, register: u16) -> Result<u32, modbus::Error> {
-    let mut array = client.read_holding_registers(register, 2)?;
+/// Reads one register over `client`, lazily opening the transport first if
+/// it isn't connected yet (the very first read, or after a previous failure
+/// closed it).
+fn try_read(connection: &DeviceConnection, client: &mut Option<tcp::Transport>, spec: &RegisterSpec) -> Result<f64, registers::RegisterError> {
+    if client.is_none() {
+        *client = Some(open_transport(&connection.address, connection.unit_id, connection.connect_timeout, connection.read_timeout)?);
+    }
 
-    array.reverse();
-    Ok((array[0] as u32) << 16 | (array[1] as u32))
+    registers::read_register(client.as_mut().unwrap(), spec)
 }
 
-#[get("/data")]
-fn data(state: &State<MinerState>) -> Result<Json<MinerData>, Status> {
-    let data = poll_solar_data(state.client_ip.clone())
+/// Reads a single register, transparently closing and re-opening the
+/// transport and retrying on failure with a capped exponential backoff
+/// between attempts, so a transient blip on the wire -- or a device that
+/// simply wasn't reachable yet -- doesn't surface as a request failure.
+fn read_register_with_retry(connection: &DeviceConnection, client: &mut Option<tcp::Transport>, spec: &RegisterSpec) -> Result<f64, MinerError> {
+    let mut last_error = match try_read(connection, client, spec) {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+
+    for backoff_ms in RETRY_BACKOFFS_MS {
+        thread::sleep(Duration::from_millis(backoff_ms));
+
+        if let Some(transport) = client.take() {
+            transport.close().ok();
+        }
+
+        match try_read(connection, client, spec) {
+            Ok(value) => return Ok(value),
+            Err(error) => last_error = error,
+        }
+    }
+
+    Err(MinerError::ModbusTransportIssue(last_error.to_string()))
+}
+
+/// Runs `poll_solar_data` on Rocket's blocking thread pool rather than an
+/// async worker thread. `poll_solar_data` does synchronous socket I/O and,
+/// on a failing read, can block for hundreds of milliseconds across the
+/// retry backoff; keeping that off the (small, shared) async worker pool
+/// means one flaky device can't stall unrelated requests or other devices'
+/// pollers.
+async fn poll_solar_data_blocking(connection: Arc<DeviceConnection>) -> Result<MinerData, MinerError> {
+    rocket::tokio::task::spawn_blocking(move || poll_solar_data(&connection))
+        .await
+        .expect("poll_solar_data task panicked")
+}
+
+#[get("/devices")]
+fn devices(connections: &State<Arc<DeviceConnections>>) -> Json<Vec<String>> {
+    let mut names: Vec<String> = connections.keys().cloned().collect();
+    names.sort();
+    Json(names)
+}
+
+#[get("/data/<device>")]
+async fn data(device: &str, connections: &State<Arc<DeviceConnections>>) -> Result<Json<MinerData>, Status> {
+    let connection = connections.get(device).ok_or(Status::NotFound)?.clone();
+    let data = poll_solar_data_blocking(connection)
+      .await
       .or_else(|_| Err(Status::InternalServerError))?;
     Ok(Json(data))
 }
 
+#[derive(Serialize)]
+struct HistorySample {
+    timestamp: u64,
+    #[serde(flatten)]
+    data: MinerData,
+}
+
+#[get("/history/<device>?<from>&<to>&<step>")]
+fn history(device: &str, histories: &State<Arc<DeviceHistories>>, from: u64, to: u64, step: Option<u64>) -> Result<Json<Vec<HistorySample>>, Status> {
+    let store = histories.get(device).ok_or(Status::NotFound)?;
+    let samples = store.query(from, to, step.unwrap_or(0))
+      .or_else(|_| Err(Status::InternalServerError))?;
+
+    Ok(Json(samples.into_iter()
+        .map(|(timestamp, data)| HistorySample { timestamp, data })
+        .collect()))
+}
+
+/// Polls every configured device and renders the results as Prometheus
+/// gauges, so operators can scrape this crate directly instead of writing a
+/// custom JSON exporter. A device that fails to poll is reported via
+/// `miner_up{device="..."} 0` rather than failing the whole scrape, so one
+/// unreachable meter doesn't blank out every other device's metrics.
+#[get("/metrics")]
+async fn metrics_route(connections: &State<Arc<DeviceConnections>>) -> (ContentType, String) {
+    let mut samples = Vec::new();
+    let mut up = Vec::new();
+
+    for (name, connection) in connections.iter() {
+        match poll_solar_data_blocking(connection.clone()).await {
+            Ok(data) => {
+                up.push((name.clone(), true));
+                samples.push((name.clone(), data));
+            }
+            Err(error) => {
+                eprintln!("[{}] Failed to poll solar data for /metrics: {}", name, error);
+                up.push((name.clone(), false));
+            }
+        }
+    }
+    samples.sort_by(|a, b| a.0.cmp(&b.0));
+    up.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut body = metrics::render_up(&up);
+    body.push_str(&metrics::render(&samples));
+
+    (ContentType::Plain, body)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn read_env_u64(key: &str) -> Option<u64> {
+    env::var(key).ok().and_then(|value| value.parse().ok())
+}
+
+/**
+ * Background fairing that samples one device's `poll_solar_data` on a fixed
+ * interval and writes each sample into its `HistoryStore`, so `/history`
+ * has something to serve without every request having to hit the analyzer
+ * itself. One of these is attached per configured device.
+ */
+fn history_poller(connection: Arc<DeviceConnection>, store: Arc<HistoryStore>, poll_interval_secs: u64) -> AdHoc {
+    AdHoc::on_liftoff("History Poller", |_| Box::pin(async move {
+        rocket::tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(poll_interval_secs));
+            loop {
+                ticker.tick().await;
+                match poll_solar_data_blocking(connection.clone()).await {
+                    Ok(sample) => {
+                        if let Err(error) = store.record(unix_now(), &sample) {
+                            eprintln!("[{}] Failed to persist history sample: {}", connection.name, error);
+                        }
+                    }
+                    Err(error) => eprintln!("[{}] Failed to poll solar data for history: {}", connection.name, error),
+                }
+            }
+        });
+    }))
+}
+
 #[launch]
 fn rocket() -> _ {
     dotenv().ok();
 
-    let client_ip = match env::var("POWER_ANALYZER_IP") {
-        Ok(value) => value,
-        Err(error) => panic!("Cannot get environment variable for power analyzer's IP: {}", error),
-    };
+    let config_path = env::var("CONFIG_PATH").unwrap_or_else(|_| "./config.toml".to_string());
+    let config = Config::load(&config_path)
+        .unwrap_or_else(|error| panic!("Cannot load device config from {}: {}", config_path, error));
+
+    let connect_timeout = Duration::from_millis(read_env_u64("MODBUS_CONNECT_TIMEOUT_MS").unwrap_or(500));
+    let read_timeout = Duration::from_millis(read_env_u64("MODBUS_READ_TIMEOUT_MS").unwrap_or(500));
+    let poll_interval_secs = read_env_u64("HISTORY_POLL_INTERVAL_SECS").unwrap_or(30);
+    let retention_secs = read_env_u64("HISTORY_RETENTION_SECS").unwrap_or(60 * 60 * 24 * 7);
+    let history_db_root = env::var("HISTORY_DB_ROOT").unwrap_or_else(|_| "./history".to_string());
+
+    let mut connections: DeviceConnections = HashMap::new();
+    let mut histories: DeviceHistories = HashMap::new();
+    let mut server = rocket::build().mount("/", routes![data, devices, history, metrics_route]);
+
+    for device in &config.device {
+        let connection = Arc::new(DeviceConnection::new(device, connect_timeout, read_timeout));
+
+        let history_path = format!("{}/{}", history_db_root, device.name);
+        let store = Arc::new(
+            HistoryStore::open(&history_path, retention_secs, poll_interval_secs)
+                .unwrap_or_else(|error| panic!("Cannot open history store for device {:?} at {}: {}", device.name, history_path, error))
+        );
+
+        server = server.attach(history_poller(connection.clone(), store.clone(), poll_interval_secs));
+
+        connections.insert(device.name.clone(), connection);
+        histories.insert(device.name.clone(), store);
+    }
 
-    let state = MinerState{ client_ip: client_ip };
-    rocket::build()
-      .mount("/", routes![data])
-      .manage(state)
+    server
+      .manage(Arc::new(connections))
+      .manage(Arc::new(histories))
 }
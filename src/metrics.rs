@@ -0,0 +1,87 @@
+use std::fmt::Write;
+
+use crate::{MinerData, PhasePower};
+
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&MinerData) -> f64,
+}
+
+const GAUGES: &[Gauge] = &[
+    Gauge { name: "miner_imported_power_total", help: "Cumulative imported active power, in watts.", value: |d| d.imported_power_total },
+    Gauge { name: "miner_imported_reactive_power_total", help: "Cumulative imported reactive power, in VAR.", value: |d| d.imported_reactive_power_total },
+    Gauge { name: "miner_exported_power_total", help: "Cumulative exported active power, in watts.", value: |d| d.exported_power_total },
+    Gauge { name: "miner_exported_reactive_power_total", help: "Cumulative exported reactive power, in VAR.", value: |d| d.exported_reactive_power_total },
+];
+
+struct PhaseGauge {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&PhasePower) -> f64,
+}
+
+const PHASE_GAUGES: &[PhaseGauge] = &[
+    PhaseGauge { name: "miner_phase_power", help: "Per-phase active power, in watts.", value: |p| p.power },
+    PhaseGauge { name: "miner_phase_reactive_power", help: "Per-phase reactive power, in VAR.", value: |p| p.reactive_power },
+    PhaseGauge { name: "miner_phase_apparent_power", help: "Per-phase apparent power, in VA.", value: |p| p.apparent_power },
+];
+
+/// Escapes a label value per the Prometheus text exposition format: a
+/// backslash, double quote, or newline inside a label value must itself be
+/// backslash-escaped, or an unsanitized device name (say, one containing a
+/// `"`) would corrupt the rest of the scrape.
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders one `miner_up` gauge per device, so a failed scrape of a single
+/// device is visible as a `0` rather than as a missing series indistinguishable
+/// from "never configured".
+pub fn render_up(statuses: &[(String, bool)]) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "# HELP miner_up Whether the last poll of this device succeeded (1) or failed (0).").ok();
+    writeln!(out, "# TYPE miner_up gauge").ok();
+    for (device, is_up) in statuses {
+        writeln!(out, "miner_up{{device=\"{}\"}} {}", escape_label_value(device), *is_up as u8).ok();
+    }
+
+    out
+}
+
+/// Renders each device's latest `MinerData` sample as Prometheus text
+/// exposition format gauges, labeled by device name (and, for per-phase
+/// gauges, phase number).
+pub fn render(samples: &[(String, MinerData)]) -> String {
+    let mut out = String::new();
+
+    for gauge in GAUGES {
+        writeln!(out, "# HELP {} {}", gauge.name, gauge.help).ok();
+        writeln!(out, "# TYPE {} gauge", gauge.name).ok();
+        for (device, data) in samples {
+            writeln!(out, "{}{{device=\"{}\"}} {}", gauge.name, escape_label_value(device), (gauge.value)(data)).ok();
+        }
+    }
+
+    for gauge in PHASE_GAUGES {
+        writeln!(out, "# HELP {} {}", gauge.name, gauge.help).ok();
+        writeln!(out, "# TYPE {} gauge", gauge.name).ok();
+        for (device, data) in samples {
+            for (phase_number, phase) in [(1, &data.phase1), (2, &data.phase2), (3, &data.phase3)] {
+                writeln!(out, "{}{{device=\"{}\",phase=\"{}\"}} {}", gauge.name, escape_label_value(device), phase_number, (gauge.value)(phase)).ok();
+            }
+        }
+    }
+
+    out
+}
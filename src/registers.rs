@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use modbus::Client;
+use modbus::tcp;
+use phf::phf_map;
+use thiserror::Error;
+
+/// The wire representation of a register's value.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+impl RegisterKind {
+    fn register_count(self) -> u16 {
+        match self {
+            RegisterKind::U16 | RegisterKind::I16 => 1,
+            RegisterKind::U32 | RegisterKind::I32 | RegisterKind::F32 => 2,
+        }
+    }
+}
+
+/// How a 32-bit register's two 16-bit words are ordered on the wire.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WordOrder {
+    /// The first word read is the most-significant 16 bits.
+    BigEndian,
+    /// The first word read is the least-significant 16 bits.
+    LittleEndian,
+}
+
+/// Describes everything needed to decode one register into a real-world value.
+#[derive(Clone, Copy)]
+pub struct RegisterSpec {
+    pub address: u16,
+    pub kind: RegisterKind,
+    pub word_order: WordOrder,
+    pub scale: f64,
+}
+
+impl RegisterSpec {
+    pub const fn new(address: u16, kind: RegisterKind, word_order: WordOrder, scale: f64) -> Self {
+        Self { address, kind, word_order, scale }
+    }
+
+    /// Returns a copy of this spec addressing the register `offset_words`
+    /// further along, used to reach the same field on a different phase.
+    pub fn offset(&self, offset_words: u16) -> Self {
+        Self { address: self.address + offset_words, ..*self }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RegisterError {
+    #[error("modbus read failed: {0}")]
+    Modbus(#[from] modbus::Error),
+    #[error("read {0} register word(s), expected 1 or 2")]
+    UnexpectedWordCount(usize),
+}
+
+fn assemble_bits(words: &[u16], word_order: WordOrder) -> Result<u32, RegisterError> {
+    match words {
+        [word] => Ok(*word as u32),
+        [first, second] => Ok(match word_order {
+            WordOrder::BigEndian => (*first as u32) << 16 | (*second as u32),
+            WordOrder::LittleEndian => (*second as u32) << 16 | (*first as u32),
+        }),
+        _ => Err(RegisterError::UnexpectedWordCount(words.len())),
+    }
+}
+
+/// Interprets already-assembled bits per `kind`, without applying scale.
+/// Split out from `read_register` so the decode logic can be unit tested
+/// without needing a live `tcp::Transport`.
+fn decode_bits(bits: u32, kind: RegisterKind) -> f64 {
+    match kind {
+        RegisterKind::U16 => (bits as u16) as f64,
+        RegisterKind::I16 => (bits as u16 as i16) as f64,
+        RegisterKind::U32 => bits as f64,
+        RegisterKind::I32 => (bits as i32) as f64,
+        RegisterKind::F32 => f32::from_bits(bits) as f64,
+    }
+}
+
+/// Reads the holding register(s) described by `spec`, reassembles the words
+/// per its word order, interprets the bits per its kind, and applies its
+/// scale factor. Returns a `RegisterError` rather than panicking if the
+/// transport ever hands back an unexpected word count, since this runs
+/// while holding the device's transport mutex locked and a panic here would
+/// poison it for good.
+pub fn read_register(client: &mut tcp::Transport, spec: &RegisterSpec) -> Result<f64, RegisterError> {
+    let words = client.read_holding_registers(spec.address, spec.kind.register_count())?;
+    let bits = assemble_bits(&words, spec.word_order)?;
+
+    Ok(decode_bits(bits, spec.kind) * spec.scale)
+}
+
+#[derive(PartialEq, Eq, Hash)]
+pub enum PhaseField {
+    Power,
+    ApparentPower,
+    ReactivePower,
+}
+
+/// Register layout for the `sungold-default` profile: an unsigned 32-bit
+/// word-swapped quantity at each address, matching the meter this crate was
+/// originally written against.
+pub static BASE_REGISTERS: phf::Map<&'static str, RegisterSpec> = phf_map! {
+    "imported_power_total" => RegisterSpec::new(0x34, RegisterKind::U32, WordOrder::LittleEndian, 1.0),
+    "imported_reactive_power_total" => RegisterSpec::new(0x36, RegisterKind::U32, WordOrder::LittleEndian, 1.0),
+    "exported_power_total" => RegisterSpec::new(0x4e, RegisterKind::U32, WordOrder::LittleEndian, 1.0),
+    "exported_reactive_power_total" => RegisterSpec::new(0x50, RegisterKind::U32, WordOrder::LittleEndian, 1.0),
+};
+
+/// Per-phase register specs for phase 1; phases 2 and 3 are reached by
+/// `offset`-ing each spec by `i * 0x2` words.
+pub fn phase_registers() -> HashMap<PhaseField, RegisterSpec> {
+    HashMap::from([
+        (PhaseField::Power, RegisterSpec::new(0x12, RegisterKind::U32, WordOrder::LittleEndian, 1.0)),
+        (PhaseField::ApparentPower, RegisterSpec::new(0x18, RegisterKind::U32, WordOrder::LittleEndian, 1.0)),
+        (PhaseField::ReactivePower, RegisterSpec::new(0x1e, RegisterKind::U32, WordOrder::LittleEndian, 1.0)),
+    ])
+}
+
+/// A named register layout: which base registers exist and how to reach the
+/// per-phase ones. Selected per-device by the `register_profile` name in
+/// `config.toml`, so a second meter with a different map only needs a new
+/// entry here rather than a code fork.
+pub struct RegisterProfile {
+    pub base: &'static phf::Map<&'static str, RegisterSpec>,
+    pub phase: fn() -> HashMap<PhaseField, RegisterSpec>,
+}
+
+static SUNGOLD_DEFAULT: RegisterProfile = RegisterProfile {
+    base: &BASE_REGISTERS,
+    phase: phase_registers,
+};
+
+/// Looks up a register profile by its config-file name. `Config::validate`
+/// rejects any name this returns `None` for before it ever reaches here.
+pub fn profile(name: &str) -> Option<&'static RegisterProfile> {
+    match name {
+        "sungold-default" => Some(&SUNGOLD_DEFAULT),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_bits_passes_single_word_through() {
+        assert_eq!(assemble_bits(&[0x1234], WordOrder::BigEndian).unwrap(), 0x1234);
+        assert_eq!(assemble_bits(&[0x1234], WordOrder::LittleEndian).unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn assemble_bits_big_endian_puts_first_word_high() {
+        assert_eq!(assemble_bits(&[0x0001, 0x0002], WordOrder::BigEndian).unwrap(), 0x0001_0002);
+    }
+
+    #[test]
+    fn assemble_bits_little_endian_puts_first_word_low() {
+        assert_eq!(assemble_bits(&[0x0001, 0x0002], WordOrder::LittleEndian).unwrap(), 0x0002_0001);
+    }
+
+    #[test]
+    fn assemble_bits_rejects_unexpected_word_count() {
+        assert!(matches!(
+            assemble_bits(&[], WordOrder::BigEndian),
+            Err(RegisterError::UnexpectedWordCount(0))
+        ));
+        assert!(matches!(
+            assemble_bits(&[1, 2, 3], WordOrder::BigEndian),
+            Err(RegisterError::UnexpectedWordCount(3))
+        ));
+    }
+
+    #[test]
+    fn decode_bits_u16_keeps_lower_word_unsigned() {
+        assert_eq!(decode_bits(0x0000_1234, RegisterKind::U16), 0x1234 as f64);
+    }
+
+    #[test]
+    fn decode_bits_i16_sign_extends_negative_values() {
+        assert_eq!(decode_bits(0x0000_ffff, RegisterKind::I16), -1.0);
+    }
+
+    #[test]
+    fn decode_bits_u32_keeps_full_width_unsigned() {
+        assert_eq!(decode_bits(0xffff_fffe, RegisterKind::U32), 4_294_967_294.0);
+    }
+
+    #[test]
+    fn decode_bits_i32_is_negative_when_high_bit_set() {
+        assert_eq!(decode_bits(0xffff_ffff, RegisterKind::I32), -1.0);
+    }
+
+    #[test]
+    fn decode_bits_f32_reinterprets_bits_as_ieee754() {
+        assert_eq!(decode_bits(1.5f32.to_bits(), RegisterKind::F32), 1.5);
+    }
+
+    #[test]
+    fn register_spec_offset_advances_address_by_words() {
+        let spec = RegisterSpec::new(0x10, RegisterKind::U32, WordOrder::BigEndian, 1.0);
+        assert_eq!(spec.offset(2).address, 0x12);
+    }
+}